@@ -1,52 +1,60 @@
 use std::cmp::{Ordering, Reverse};
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 
 use bitvec::vec::BitVec;
-use serde::{Deserialize, Serialize};
-
-/// Huffman tree node.
-/// Convention: Left => false (0), Right => true (1)
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Tree {
-    Leaf {
-        token: u8,
-        freq: u64,
-    },
-    Node {
-        left: Box<Tree>,
-        right: Box<Tree>,
-        freq: u64,
-    },
-}
 
-impl Tree {
-    fn new_leaf(token: u8, freq: u64) -> Self {
-        Tree::Leaf { token, freq }
-    }
+/// Container magic bytes identifying a `.huf` file, followed by a version
+/// byte so the format can evolve without silently misparsing old files.
+const MAGIC: &[u8; 4] = b"HUF1";
+const FORMAT_VERSION: u8 = 1;
 
-    fn new_node(left: Tree, right: Tree) -> Self {
-        let freq = left.freq() + right.freq();
-        Tree::Node {
-            left: Box::new(left),
-            right: Box::new(right),
-            freq,
-        }
-    }
+/// Buffer size used by the streaming encode/decode paths so peak memory
+/// stays bounded regardless of input size.
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
 
-    pub fn freq(&self) -> u64 {
-        match self {
-            Tree::Leaf { freq, .. } => *freq,
-            Tree::Node { freq, .. } => *freq,
-        }
-    }
+/// Maximum number of nodes a Huffman tree over a byte alphabet can ever need:
+/// 256 leaves plus at most 255 internal nodes.
+const MAX_TREE_NODES: usize = 511;
+
+/// Maximum canonical code length the encoder will ever assign. A 256-symbol
+/// alphabet with a sufficiently skewed frequency distribution (e.g.
+/// Fibonacci-ratio frequencies, which produce a maximally unbalanced tree)
+/// can demand codes up to `alphabet_size - 1` bits long, which would overflow
+/// the `u32` accumulator `assign_canonical_codes` shifts codes through.
+/// Lengths are clamped to this bound and rebalanced well before that point,
+/// leaving ample headroom under 32 bits.
+const MAX_CODE_LEN: u8 = 24;
+
+/// A node in the flat tree arena built during construction. Leaves have
+/// `token: Some(_)`; internal nodes link to their children and both kinds
+/// link back to their parent so code lengths can be read off by walking up
+/// instead of recursing down. Frequencies only matter while the min-heap is
+/// picking merge order (tracked there via `HeapNode::freq`), so nodes don't
+/// need to carry their own once placed in the arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TreeNode {
+    token: Option<u8>,
+    left: Option<usize>,
+    right: Option<usize>,
+    parent: Option<usize>,
+}
+
+/// Huffman tree, built bottom-up as a flat, index-addressed arena rather than
+/// a recursively boxed structure: every node lives contiguously in `nodes`,
+/// and traversal (there is none left but the leaf-to-root length walk) never
+/// recurses, so pathological inputs can't blow the stack.
+struct TreeArena {
+    nodes: Vec<TreeNode>,
+    root: usize,
 }
 
 /// Used to get deterministic ordering in the heap.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct HeapNode {
     freq: u64,
     id: usize,
-    tree: Tree,
+    index: usize,
 }
 
 impl Ord for HeapNode {
@@ -101,15 +109,126 @@ impl EncoderTable {
     }
 }
 
-/// Encoded message: contains the Huffman tree, the encoded bits and the
-/// original length of the input (required to properly decode edge-cases).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Encoded message: contains the canonical code lengths (sufficient to
+/// rebuild the Huffman codes without shipping the tree shape), the encoded
+/// bits and the original length of the input (required to properly decode
+/// edge-cases).
+///
+/// `code_lengths` is a sparse `(token, length)` list rather than a
+/// `[u8; 256]` so alphabets smaller than 256 symbols don't pay for absent
+/// entries.
+#[derive(Debug, Clone)]
 pub struct Message {
-    pub tree: Tree,
+    pub code_lengths: Vec<(u8, u8)>,
     pub encoded_data: BitVec,
     pub original_len: usize,
 }
 
+impl Message {
+    /// Serialize into the on-disk `.huf` container: magic bytes, a version
+    /// byte, `original_len` as little-endian `u64`, the code-length table,
+    /// then the packed payload bytes with a trailing pad-bit count so the
+    /// reader knows exactly where the real bitstream ends.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Self::header_bytes(self.original_len as u64, &self.code_lengths);
+
+        let (packed, pad) = pack_bits(&self.encoded_data);
+        out.extend_from_slice(&packed);
+        out.push(pad);
+
+        out
+    }
+
+    /// Parse a `.huf` container, validating the magic bytes and version up
+    /// front so callers get a clean error instead of a raw parse failure
+    /// deep in the bitstream.
+    pub fn from_bytes(data: &[u8]) -> Result<Message, String> {
+        let mut cursor = data;
+        let (original_len, code_lengths) = Self::read_header(&mut cursor)?;
+
+        if cursor.is_empty() {
+            return Err("Truncated .huf file: missing payload".to_string());
+        }
+        let (payload, pad_byte) = cursor.split_at(cursor.len() - 1);
+        let pad = pad_byte[0];
+
+        Ok(Message {
+            code_lengths,
+            encoded_data: unpack_bits(payload, pad),
+            original_len,
+        })
+    }
+
+    /// Build the fixed header shared by `to_bytes` and the streaming encoder:
+    /// magic, version, `original_len`, then the code-length table.
+    fn header_bytes(original_len: u64, code_lengths: &[(u8, u8)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&original_len.to_le_bytes());
+        out.extend_from_slice(&(code_lengths.len() as u16).to_le_bytes());
+        for &(token, len) in code_lengths {
+            out.push(token);
+            out.push(len);
+        }
+        out
+    }
+
+    /// The header for an empty input: no symbols, `original_len` of 0.
+    fn empty_header() -> Vec<u8> {
+        Self::header_bytes(0, &[])
+    }
+
+    /// Read and validate a `.huf` header from any `Read`, so `from_bytes`
+    /// and `decode_stream` parse the exact same format from a slice or a
+    /// live stream respectively.
+    fn read_header<R: Read>(reader: &mut R) -> Result<(usize, Vec<(u8, u8)>), String> {
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|e| format!("Failed to read .huf header: {}", e))?;
+        if &magic != MAGIC {
+            return Err("Not a .huf file: bad magic bytes".to_string());
+        }
+
+        let mut version = [0u8; 1];
+        reader
+            .read_exact(&mut version)
+            .map_err(|e| format!("Failed to read .huf header: {}", e))?;
+        if version[0] != FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported .huf container version {} (expected {})",
+                version[0], FORMAT_VERSION
+            ));
+        }
+
+        let mut len_bytes = [0u8; 8];
+        reader
+            .read_exact(&mut len_bytes)
+            .map_err(|e| format!("Failed to read .huf header: {}", e))?;
+        let original_len = u64::from_le_bytes(len_bytes) as usize;
+
+        let mut count_bytes = [0u8; 2];
+        reader
+            .read_exact(&mut count_bytes)
+            .map_err(|e| format!("Failed to read .huf header: {}", e))?;
+        let count = u16::from_le_bytes(count_bytes) as usize;
+
+        let mut table_bytes = vec![0u8; count * 2];
+        reader
+            .read_exact(&mut table_bytes)
+            .map_err(|e| format!("Failed to read code-length table: {}", e))?;
+        let code_lengths: Vec<(u8, u8)> = table_bytes
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+
+        validate_code_lengths(&code_lengths)?;
+
+        Ok((original_len, code_lengths))
+    }
+}
+
 /// Public API
 pub struct Huffman;
 
@@ -121,7 +240,7 @@ impl Huffman {
     pub fn encode(bytes: &[u8]) -> Result<Message, String> {
         if bytes.is_empty() {
             return Ok(Message {
-                tree: Tree::new_leaf(0, 0),
+                code_lengths: Vec::new(),
                 encoded_data: BitVec::new(),
                 original_len: 0,
             });
@@ -129,7 +248,8 @@ impl Huffman {
 
         let freq_table = construct_freqs_table(bytes);
         let tree = construct_huffman_tree(&freq_table)?;
-        let encoder = construct_encoder_table(&tree);
+        let code_lengths = limit_code_lengths(&compute_code_lengths(&tree), MAX_CODE_LEN);
+        let encoder = assign_canonical_codes(&code_lengths);
 
         let mut encoded_data = BitVec::new();
 
@@ -139,63 +259,104 @@ impl Huffman {
         }
 
         Ok(Message {
-            tree,
+            code_lengths,
             encoded_data,
             original_len: bytes.len(),
         })
     }
 
     /// Decode a `Message` back into the original bytes.
+    ///
+    /// The canonical codes (and the decode tree walked below) are rebuilt
+    /// from `message.code_lengths` alone; no tree shape is ever shipped.
     pub fn decode(message: &Message) -> Result<Vec<u8>, String> {
         if message.original_len == 0 {
             return Ok(Vec::new());
         }
 
-        // Special-case: if the tree is a single leaf, then the encoding
-        // uses a non-empty code per symbol.
-        if let Tree::Leaf { token, .. } = &message.tree {
-            return Ok(vec![*token; message.original_len]);
+        // Special-case: a single-symbol alphabet has exactly one length-1
+        // entry and every bit decodes to that symbol.
+        if let [(token, _)] = message.code_lengths[..] {
+            return Ok(vec![token; message.original_len]);
         }
 
+        let root = build_decode_trie(&message.code_lengths);
+
         let mut decoded = Vec::with_capacity(message.original_len);
-        let mut node = &message.tree;
+        let mut node = &root;
 
         for bit in message.encoded_data.iter() {
-            match node {
-                &Tree::Node {
-                    ref left,
-                    ref right,
-                    ..
-                } => {
-                    let next: &Tree = if *bit { right.as_ref() } else { left.as_ref() };
-                    node = next;
-
-                    if let &Tree::Leaf { token, .. } = node {
-                        decoded.push(token);
-                        node = &message.tree;
-                        if decoded.len() == message.original_len {
-                            break;
-                        }
-                    }
-                }
-                &Tree::Leaf { token, .. } => {
-                    // This could only happen for degenerate trees.
-                    // If so, push the token and reset the node.
-                    decoded.push(token);
-                    node = &message.tree;
-                    if decoded.len() == message.original_len {
-                        break;
-                    }
+            let next: &DecodeTrie = if *bit {
+                node.right.as_deref()
+            } else {
+                node.left.as_deref()
+            }
+            .ok_or_else(|| "Corrupt bitstream: no matching code path".to_string())?;
+            node = next;
+
+            if let Some(token) = node.token {
+                decoded.push(token);
+                node = &root;
+                if decoded.len() == message.original_len {
+                    break;
                 }
             }
         }
 
-        // If traversal ended exactly on a leaf without another bit to trigger
-        // pushing it during the loop.
-        if decoded.len() < message.original_len {
-            if let Tree::Leaf { token, .. } = node {
-                decoded.push(*token);
+        if decoded.len() != message.original_len {
+            return Err(format!(
+                "Decoded length mismatch: expected {}, got {}",
+                message.original_len,
+                decoded.len()
+            ));
+        }
+
+        Ok(decoded)
+    }
+
+    /// Table-driven decoder: produces output identical to `decode`, but reads
+    /// `TABLE_BITS` bits at a time through a compiled lookup table instead of
+    /// chasing one pointer per bit, trading a one-off compile step for far
+    /// fewer, more cache-friendly jumps while decoding.
+    pub fn decode_fast(message: &Message) -> Result<Vec<u8>, String> {
+        if message.original_len == 0 {
+            return Ok(Vec::new());
+        }
+
+        if let [(token, _)] = message.code_lengths[..] {
+            return Ok(vec![token; message.original_len]);
+        }
+
+        let mut decoder = CompiledDecoder::new(&message.code_lengths)?;
+
+        let mut decoded = Vec::with_capacity(message.original_len);
+        let total_bits = message.encoded_data.len();
+        let mut pos = 0usize;
+        let mut state = decoder.root;
+
+        while pos < total_bits && decoded.len() < message.original_len {
+            let mut window: u8 = 0;
+            for i in 0..TABLE_BITS {
+                let bit = message
+                    .encoded_data
+                    .get(pos + i)
+                    .as_deref()
+                    .copied()
+                    .unwrap_or(false);
+                window = (window << 1) | bit as u8;
             }
+
+            let entry = decoder.lookup(state, window)?;
+
+            for &symbol in &entry.symbols {
+                if decoded.len() >= message.original_len {
+                    break;
+                }
+                decoded.push(symbol);
+            }
+
+            pos += entry.bits_consumed as usize;
+            state = entry.resume;
         }
 
         if decoded.len() != message.original_len {
@@ -208,6 +369,259 @@ impl Huffman {
 
         Ok(decoded)
     }
+
+    /// Stream-encode `reader` straight into the `.huf` container format on
+    /// `writer`, never holding more than `STREAM_BUFFER_SIZE` bytes of input
+    /// or one accumulating byte of output in memory at a time.
+    ///
+    /// Two passes over `reader` are required: the first tallies symbol
+    /// frequencies, the second emits packed code bits once the canonical
+    /// table is known, so `reader` must support seeking back to the start.
+    pub fn encode_stream<R: Read + Seek, W: Write>(
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), String> {
+        let mut freq_table = FreqsTable::new();
+        let mut original_len: u64 = 0;
+        let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+
+        loop {
+            let n = reader
+                .read(&mut buf)
+                .map_err(|e| format!("Failed to read input: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            for &b in &buf[..n] {
+                freq_table.add(b, 1);
+            }
+            original_len += n as u64;
+        }
+
+        if original_len == 0 {
+            let mut header = Message::empty_header();
+            header.push(0); // pad-bit count trailer, matching an empty `to_bytes()` payload
+            writer
+                .write_all(&header)
+                .map_err(|e| format!("Failed to write output: {}", e))?;
+            return Ok(());
+        }
+
+        let tree = construct_huffman_tree(&freq_table)?;
+        let code_lengths = limit_code_lengths(&compute_code_lengths(&tree), MAX_CODE_LEN);
+        let encoder = assign_canonical_codes(&code_lengths);
+
+        writer
+            .write_all(&Message::header_bytes(original_len, &code_lengths))
+            .map_err(|e| format!("Failed to write output header: {}", e))?;
+
+        reader
+            .seek(SeekFrom::Start(0))
+            .map_err(|e| format!("Failed to rewind input: {}", e))?;
+
+        let mut bit_writer = BitWriter::new(writer);
+        loop {
+            let n = reader
+                .read(&mut buf)
+                .map_err(|e| format!("Failed to read input: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            for &b in &buf[..n] {
+                bit_writer
+                    .write_bits(encoder.get(b))
+                    .map_err(|e| format!("Failed to write output: {}", e))?;
+            }
+        }
+
+        bit_writer
+            .finish()
+            .map_err(|e| format!("Failed to write output: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Stream-decode a `.huf` container from `reader` into `writer`, writing
+    /// decoded bytes in `STREAM_BUFFER_SIZE` chunks instead of materializing
+    /// the whole output.
+    pub fn decode_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<(), String> {
+        let (original_len, code_lengths) = Message::read_header(reader)?;
+
+        if original_len == 0 {
+            return Ok(());
+        }
+
+        if let [(token, _)] = code_lengths[..] {
+            let mut remaining = original_len;
+            let chunk = vec![token; STREAM_BUFFER_SIZE.min(original_len)];
+            while remaining > 0 {
+                let n = remaining.min(chunk.len());
+                writer
+                    .write_all(&chunk[..n])
+                    .map_err(|e| format!("Failed to write output: {}", e))?;
+                remaining -= n;
+            }
+            return Ok(());
+        }
+
+        let (nodes, root) = build_decode_arena(&code_lengths);
+
+        let mut out_buf = Vec::with_capacity(STREAM_BUFFER_SIZE);
+        let mut emitted = 0usize;
+        let mut state = root;
+
+        // Read one byte ahead so the final byte of the payload (the
+        // pad-bit-count trailer) is recognized by EOF instead of being fed
+        // to the decoder as if it were code bits.
+        let mut current = [0u8; 1];
+        let mut have_current = reader
+            .read(&mut current)
+            .map_err(|e| format!("Failed to read payload: {}", e))?
+            == 1;
+
+        while have_current && emitted < original_len {
+            let byte = current[0];
+
+            let mut next = [0u8; 1];
+            let read = reader
+                .read(&mut next)
+                .map_err(|e| format!("Failed to read payload: {}", e))?;
+            if read == 0 {
+                // `byte` was the pad-count trailer, not payload.
+                break;
+            }
+
+            for i in (0..8).rev() {
+                if emitted >= original_len {
+                    break;
+                }
+                let bit = (byte >> i) & 1 == 1;
+                state = if bit { nodes[state].right } else { nodes[state].left }
+                    .ok_or_else(|| "Corrupt bitstream: no matching code path".to_string())?;
+
+                if let Some(token) = nodes[state].token {
+                    out_buf.push(token);
+                    emitted += 1;
+                    state = root;
+                    if out_buf.len() == STREAM_BUFFER_SIZE {
+                        writer
+                            .write_all(&out_buf)
+                            .map_err(|e| format!("Failed to write output: {}", e))?;
+                        out_buf.clear();
+                    }
+                }
+            }
+
+            current = next;
+            have_current = true;
+        }
+
+        if !out_buf.is_empty() {
+            writer
+                .write_all(&out_buf)
+                .map_err(|e| format!("Failed to write output: {}", e))?;
+        }
+
+        if emitted != original_len {
+            return Err(format!(
+                "Decoded length mismatch: expected {}, got {}",
+                original_len, emitted
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Accumulates bits into whole bytes and writes each one through as soon as
+/// it fills, so streaming encode never buffers more than a single pending
+/// byte of output.
+struct BitWriter<'a, W: Write> {
+    writer: &'a mut W,
+    current: u8,
+    count: u8,
+}
+
+impl<'a, W: Write> BitWriter<'a, W> {
+    fn new(writer: &'a mut W) -> Self {
+        BitWriter {
+            writer,
+            current: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, bits: &BitVec) -> io::Result<()> {
+        for bit in bits.iter().by_vals() {
+            self.current = (self.current << 1) | bit as u8;
+            self.count += 1;
+            if self.count == 8 {
+                self.writer.write_all(&[self.current])?;
+                self.current = 0;
+                self.count = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush any partial final byte, zero-padded, followed by the pad-bit
+    /// count trailer the container format expects.
+    fn finish(mut self) -> io::Result<()> {
+        let pad = if self.count > 0 {
+            let pad = 8 - self.count;
+            self.current <<= pad;
+            self.writer.write_all(&[self.current])?;
+            pad
+        } else {
+            0
+        };
+        self.writer.write_all(&[pad])
+    }
+}
+
+/// Pack a bit sequence into byte-aligned storage, zero-padding the final
+/// byte. Returns the packed bytes and the number of padding bits appended,
+/// so a reader can strip them back off.
+fn pack_bits(bits: &BitVec) -> (Vec<u8>, u8) {
+    let mut bytes = Vec::with_capacity(bits.len().div_ceil(8));
+    let mut current = 0u8;
+    let mut count = 0u8;
+
+    for bit in bits.iter().by_vals() {
+        current = (current << 1) | bit as u8;
+        count += 1;
+        if count == 8 {
+            bytes.push(current);
+            current = 0;
+            count = 0;
+        }
+    }
+
+    let pad = if count > 0 {
+        let pad = 8 - count;
+        current <<= pad;
+        bytes.push(current);
+        pad
+    } else {
+        0
+    };
+
+    (bytes, pad)
+}
+
+/// Inverse of `pack_bits`: unpack bytes into a bit sequence and trim the
+/// trailing padding bits recorded alongside the payload.
+fn unpack_bits(bytes: &[u8], pad: u8) -> BitVec {
+    let mut bits = BitVec::with_capacity(bytes.len() * 8);
+    for &byte in bytes {
+        for i in (0..8).rev() {
+            bits.push((byte >> i) & 1 == 1);
+        }
+    }
+
+    let new_len = bits.len().saturating_sub(pad as usize);
+    bits.truncate(new_len);
+    bits
 }
 
 /// Build frequency table from input bytes.
@@ -219,22 +633,30 @@ fn construct_freqs_table(data: &[u8]) -> FreqsTable {
     freqs
 }
 
-/// Construct Huffman tree from frequency table.
+/// Construct Huffman tree from frequency table as a flat node arena.
 /// Returns Error if there are no symbols.
-fn construct_huffman_tree(freq_table: &FreqsTable) -> Result<Tree, String> {
+fn construct_huffman_tree(freq_table: &FreqsTable) -> Result<TreeArena, String> {
+    let mut nodes: Vec<TreeNode> = Vec::with_capacity(MAX_TREE_NODES);
+
     // Build a minheap using Reverse. HeapNode::cmp orders by freq then id.
     let mut minheap: BinaryHeap<std::cmp::Reverse<HeapNode>> = BinaryHeap::new();
     let mut next_id: usize = 0;
 
     for (token, &freq) in freq_table.freqs.iter().enumerate() {
         if freq > 0 {
-            let node = HeapNode {
+            let index = nodes.len();
+            nodes.push(TreeNode {
+                token: Some(token as u8),
+                left: None,
+                right: None,
+                parent: None,
+            });
+            minheap.push(std::cmp::Reverse(HeapNode {
                 freq,
                 id: next_id,
-                tree: Tree::new_leaf(token as u8, freq),
-            };
+                index,
+            }));
             next_id = next_id.saturating_add(1);
-            minheap.push(std::cmp::Reverse(node));
         }
     }
 
@@ -242,63 +664,391 @@ fn construct_huffman_tree(freq_table: &FreqsTable) -> Result<Tree, String> {
         return Err("Empty frequency table: cannot construct Huffman tree".to_string());
     }
 
-    // If there's only one symbol, return the single leaf. We'll ensure in the encoder
-    // that it gets assigned a non-empty code.
+    // If there's only one symbol, the arena keeps just that single leaf. The
+    // encoder ensures it still gets assigned a non-empty code.
     while minheap.len() > 1 {
         let Reverse(left_node) = minheap.pop().unwrap();
         let Reverse(right_node) = minheap.pop().unwrap();
 
-        let combined_tree = Tree::new_node(left_node.tree, right_node.tree);
         let combined_freq = left_node.freq + right_node.freq;
+        let new_index = nodes.len();
+        nodes.push(TreeNode {
+            token: None,
+            left: Some(left_node.index),
+            right: Some(right_node.index),
+            parent: None,
+        });
+        nodes[left_node.index].parent = Some(new_index);
+        nodes[right_node.index].parent = Some(new_index);
 
-        let new_node = HeapNode {
+        minheap.push(std::cmp::Reverse(HeapNode {
             freq: combined_freq,
             id: next_id,
-            tree: combined_tree,
-        };
+            index: new_index,
+        }));
         next_id = next_id.saturating_add(1);
-        minheap.push(std::cmp::Reverse(new_node));
     }
 
-    let root = minheap.pop().unwrap().0.tree;
-    Ok(root)
+    let root = minheap.pop().unwrap().0.index;
+    Ok(TreeArena { nodes, root })
 }
 
-/// Build encoder table from the Huffman tree.
+/// Record each symbol's code *length* (its depth in the tree) without
+/// recording the path that got it there; canonical assignment below
+/// reconstructs the actual codes from lengths alone. Depth is read off by
+/// walking each leaf up to the root via `parent` links, so no recursive
+/// traversal is needed.
 ///
-/// Special handling:
-/// - If the tree consists of a single leaf, assign a non-empty code (single 0 bit)
-///   to that symbol so that encoding produces bits to represent repeated occurrences.
-fn construct_encoder_table(tree: &Tree) -> EncoderTable {
-    let mut encoder = EncoderTable::new();
-
-    fn traverse(node: &Tree, code: &mut BitVec, table: &mut EncoderTable) {
-        match node {
-            Tree::Leaf { token, .. } => {
-                table.set(*token, code.clone());
+/// Special handling: a single-leaf arena has depth 0, but the encoder must
+/// still emit a bit per occurrence, so that symbol is forced to length 1.
+fn compute_code_lengths(tree: &TreeArena) -> Vec<(u8, u8)> {
+    if tree.nodes.len() == 1 {
+        let token = tree.nodes[tree.root]
+            .token
+            .expect("single-node arena is a leaf");
+        return vec![(token, 1)];
+    }
+
+    let mut lengths = Vec::new();
+    for (index, node) in tree.nodes.iter().enumerate() {
+        if let Some(token) = node.token {
+            let mut depth: u8 = 0;
+            let mut current = index;
+            while let Some(parent) = tree.nodes[current].parent {
+                depth += 1;
+                current = parent;
             }
-            Tree::Node { left, right, .. } => {
-                code.push(false);
-                traverse(left, code, table);
-                code.pop();
-
-                code.push(true);
-                traverse(right, code, table);
-                code.pop();
+            lengths.push((token, depth));
+        }
+    }
+    lengths
+}
+
+/// Clamp any code lengths above `max_len` down to the limit and rebalance
+/// the rest so the result still satisfies Kraft's equality (i.e. is still
+/// realizable as a complete prefix code).
+///
+/// Folding an overlong code into the `max_len` bucket can only ever *raise*
+/// its weight (a shorter code always represents a larger share of the code
+/// space), so the histogram's scaled Kraft sum is tracked exactly as a
+/// `u64` and repeatedly corrected — pushing one leaf at a time down from the
+/// deepest bucket with room, which provably lowers the sum by exactly one
+/// scaled unit per step — until it lands back on the exact target. This is
+/// the same one-leaf-at-a-time rebalancing DEFLATE-style encoders use to
+/// keep codes length-limited without a full package-merge pass, but driven
+/// off the exact sum rather than a count of how many codes were clamped,
+/// since a clamped code's excess depends on how far it overflowed (half a
+/// scaled unit for one level, approaching a whole unit for many) when
+/// (unlike DEFLATE's incremental tree build) lengths can overflow the limit
+/// by far more than one level.
+fn limit_code_lengths(code_lengths: &[(u8, u8)], max_len: u8) -> Vec<(u8, u8)> {
+    if code_lengths.is_empty() {
+        return Vec::new();
+    }
+
+    let mut bl_count = vec![0u64; max_len as usize + 1];
+    for &(_, len) in code_lengths {
+        bl_count[len.min(max_len) as usize] += 1;
+    }
+
+    let target = 1u64 << max_len;
+    let mut current: u64 = bl_count
+        .iter()
+        .enumerate()
+        .map(|(len, &count)| count << (max_len as usize - len))
+        .sum();
+
+    while current > target {
+        let mut bits = max_len as usize - 1;
+        while bits > 0 && bl_count[bits] == 0 {
+            bits -= 1;
+        }
+        if bits == 0 && bl_count[0] == 0 {
+            // Mathematically unreachable for `max_len >= ceil(log2(symbol
+            // count))`, which always holds here (max_len is 24, alphabets
+            // are at most 256 symbols): bail out rather than underflow.
+            break;
+        }
+        bl_count[bits] -= 1;
+        bl_count[bits + 1] += 2;
+        bl_count[max_len as usize] -= 1;
+        current -= 1;
+    }
+
+    let mut symbols = code_lengths.to_vec();
+    symbols.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    let mut symbols = symbols.into_iter();
+
+    let mut rebalanced = Vec::with_capacity(code_lengths.len());
+    for (len, &count) in bl_count.iter().enumerate().skip(1) {
+        for _ in 0..count {
+            let (token, _) = symbols
+                .next()
+                .expect("bl_count total matches code_lengths.len()");
+            rebalanced.push((token, len as u8));
+        }
+    }
+
+    rebalanced
+}
+
+/// Validate a code-length table read from an untrusted `.huf` file before
+/// it's used to rebuild any codes: lengths must fit within what the encoder
+/// could ever legitimately produce, tokens must be unique, and — aside from
+/// the single-symbol special case, which is forced to length 1 with no real
+/// code bits — the lengths must satisfy Kraft's equality, i.e. form a
+/// *complete* prefix code exactly as canonical construction guarantees.
+/// Rejecting anything else here means a corrupt or hand-crafted container
+/// fails with a clean error instead of surfacing a panic deeper in decoding.
+fn validate_code_lengths(code_lengths: &[(u8, u8)]) -> Result<(), String> {
+    if code_lengths.is_empty() {
+        return Ok(());
+    }
+
+    let mut seen = [false; 256];
+    for &(token, len) in code_lengths {
+        if len == 0 || len > MAX_CODE_LEN {
+            return Err(format!(
+                "Corrupt .huf header: invalid code length {} for token {}",
+                len, token
+            ));
+        }
+        if std::mem::replace(&mut seen[token as usize], true) {
+            return Err(format!(
+                "Corrupt .huf header: duplicate code-length entry for token {}",
+                token
+            ));
+        }
+    }
+
+    if code_lengths.len() == 1 {
+        return Ok(());
+    }
+
+    // Kraft's equality, computed exactly in fixed point: scaling every
+    // length up to MAX_CODE_LEN, a complete prefix code's lengths sum to
+    // exactly `1 << MAX_CODE_LEN`.
+    let total: u64 = code_lengths
+        .iter()
+        .map(|&(_, len)| 1u64 << (MAX_CODE_LEN - len))
+        .sum();
+    if total != 1u64 << MAX_CODE_LEN {
+        return Err(
+            "Corrupt .huf header: code lengths do not form a complete prefix code".to_string(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Derive canonical Huffman codes from code lengths alone (HPACK/QPACK
+/// style): sort symbols by `(length, byte value)`, then walk them in order
+/// assigning sequential codes, left-shifting whenever the length grows.
+///
+/// This is deterministic given only the lengths, so the encoder and decoder
+/// never need to agree on anything but `code_lengths`.
+fn assign_canonical_codes(code_lengths: &[(u8, u8)]) -> EncoderTable {
+    let mut symbols = code_lengths.to_vec();
+    symbols.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+    let mut table = EncoderTable::new();
+    let mut code: u32 = 0;
+    let mut prev_len = symbols.first().map(|&(_, len)| len).unwrap_or(0);
+
+    for &(token, len) in &symbols {
+        code <<= len - prev_len;
+
+        let mut bits = BitVec::new();
+        for i in (0..len).rev() {
+            bits.push((code >> i) & 1 == 1);
+        }
+        table.set(token, bits);
+
+        code += 1;
+        prev_len = len;
+    }
+
+    table
+}
+
+/// Node of the decode-side trie rebuilt from canonical code lengths.
+/// Only used transiently inside `decode`; never serialized.
+#[derive(Debug, Default)]
+struct DecodeTrie {
+    token: Option<u8>,
+    left: Option<Box<DecodeTrie>>,
+    right: Option<Box<DecodeTrie>>,
+}
+
+/// Rebuild the canonical decode trie from code lengths: derive the same
+/// codes `assign_canonical_codes` would, then insert each one as a bit path.
+fn build_decode_trie(code_lengths: &[(u8, u8)]) -> DecodeTrie {
+    let encoder = assign_canonical_codes(code_lengths);
+    let mut root = DecodeTrie::default();
+
+    for (token, _) in code_lengths {
+        let code = encoder.get(*token);
+        let mut node = &mut root;
+        for bit in code.iter().by_vals() {
+            let branch = if bit { &mut node.right } else { &mut node.left };
+            node = branch.get_or_insert_with(|| Box::new(DecodeTrie::default()));
+        }
+        node.token = Some(*token);
+    }
+
+    root
+}
+
+/// Number of bits consumed per lookup by the compiled table decoder.
+const TABLE_BITS: usize = 8;
+const TABLE_SIZE: usize = 1 << TABLE_BITS;
+
+/// Arena node for the compiled decoder: same shape as `DecodeTrie`, but
+/// addressed by index rather than `Box` pointers so a lookup table can cheaply
+/// record "resume decoding from node N" as a plain `usize`.
+#[derive(Debug, Clone, Copy)]
+struct ArenaNode {
+    token: Option<u8>,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+/// One compiled table entry: the symbols a `TABLE_BITS`-bit window decodes
+/// to, how many bits it actually consumed (always `TABLE_BITS`, since short
+/// windows are padded by the caller), and which arena node to resume from on
+/// the next window when a code straddles the boundary.
+#[derive(Debug, Clone, Default)]
+struct TableEntry {
+    symbols: Vec<u8>,
+    bits_consumed: u8,
+    resume: usize,
+}
+
+/// Lazily-compiled set of `TABLE_BITS`-bit lookup tables, one per arena node
+/// that a code has ever straddled into. Mirrors `bitstream-io`'s
+/// `compile_read_tree`: the root table is compiled up front, and whenever a
+/// code straddles a window boundary the internal node it resumes from gets
+/// its own sub-table, compiled on first use and keyed by that node.
+struct CompiledDecoder {
+    nodes: Vec<ArenaNode>,
+    root: usize,
+    tables: Vec<[TableEntry; TABLE_SIZE]>,
+    table_for_node: HashMap<usize, usize>,
+}
+
+impl CompiledDecoder {
+    fn new(code_lengths: &[(u8, u8)]) -> Result<Self, String> {
+        let (nodes, root) = build_decode_arena(code_lengths);
+        let mut decoder = CompiledDecoder {
+            nodes,
+            root,
+            tables: Vec::new(),
+            table_for_node: HashMap::new(),
+        };
+        decoder.compile_table(root)?;
+        Ok(decoder)
+    }
+
+    fn lookup(&mut self, state: usize, window: u8) -> Result<TableEntry, String> {
+        let table_id = self.compile_table(state)?;
+        Ok(self.tables[table_id][window as usize].clone())
+    }
+
+    /// Return the table index for `start`, compiling it on first use.
+    fn compile_table(&mut self, start: usize) -> Result<usize, String> {
+        if let Some(&id) = self.table_for_node.get(&start) {
+            return Ok(id);
+        }
+
+        let mut entries = Vec::with_capacity(TABLE_SIZE);
+        for window in 0..TABLE_SIZE {
+            entries.push(self.compile_window(start, window as u8)?);
+        }
+        let entries: [TableEntry; TABLE_SIZE] = entries
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("exactly TABLE_SIZE entries were pushed above"));
+
+        let id = self.tables.len();
+        self.tables.push(entries);
+        self.table_for_node.insert(start, id);
+        Ok(id)
+    }
+
+    /// Walk `TABLE_BITS` bits of `window` from `start`, emitting every
+    /// symbol whose code completes along the way and resetting to the real
+    /// root after each one, since codes never span symbol boundaries.
+    ///
+    /// `code_lengths` can come straight from an untrusted `Message` built by
+    /// hand rather than by a real `encode()` call, so a canonical trie built
+    /// from it isn't guaranteed to have a branch for every bit sequence;
+    /// that case is reported the same way `decode`'s trie walk reports it,
+    /// rather than assumed away.
+    fn compile_window(&self, start: usize, window: u8) -> Result<TableEntry, String> {
+        let mut symbols = Vec::new();
+        let mut current = start;
+
+        for i in (0..TABLE_BITS).rev() {
+            let bit = (window >> i) & 1 == 1;
+            current = if bit {
+                self.nodes[current].right
+            } else {
+                self.nodes[current].left
+            }
+            .ok_or_else(|| "Corrupt bitstream: no matching code path".to_string())?;
+
+            if let Some(token) = self.nodes[current].token {
+                symbols.push(token);
+                current = self.root;
             }
         }
+
+        Ok(TableEntry {
+            symbols,
+            bits_consumed: TABLE_BITS as u8,
+            resume: current,
+        })
     }
+}
+
+/// Same shape as `build_decode_trie`, but flattened into an index-addressed
+/// arena so compiled table entries can cheaply reference "resume here".
+fn build_decode_arena(code_lengths: &[(u8, u8)]) -> (Vec<ArenaNode>, usize) {
+    let encoder = assign_canonical_codes(code_lengths);
+    let root = 0usize;
+    let mut nodes = vec![ArenaNode {
+        token: None,
+        left: None,
+        right: None,
+    }];
 
-    if let Tree::Leaf { token, .. } = tree {
-        let mut code = BitVec::new();
-        code.push(false);
-        encoder.set(*token, code);
-        return encoder;
+    for (token, _) in code_lengths {
+        let code = encoder.get(*token);
+        let mut current = root;
+        for bit in code.iter().by_vals() {
+            let next = if bit {
+                nodes[current].right
+            } else {
+                nodes[current].left
+            };
+            let next = next.unwrap_or_else(|| {
+                nodes.push(ArenaNode {
+                    token: None,
+                    left: None,
+                    right: None,
+                });
+                nodes.len() - 1
+            });
+            if bit {
+                nodes[current].right = Some(next);
+            } else {
+                nodes[current].left = Some(next);
+            }
+            current = next;
+        }
+        nodes[current].token = Some(*token);
     }
 
-    let mut code = BitVec::new();
-    traverse(tree, &mut code, &mut encoder);
-    encoder
+    (nodes, root)
 }
 
 #[cfg(test)]
@@ -332,4 +1082,162 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_message_carries_lengths_not_tree_shape() -> Result<(), String> {
+        let text = "aaaaaaaabbbbccdd";
+        let message = Huffman::encode(text.as_bytes())?;
+
+        // One length entry per distinct symbol, nothing shaped like a tree.
+        assert_eq!(message.code_lengths.len(), 4);
+
+        let decoded = Huffman::decode(&message)?;
+        assert_eq!(text.as_bytes(), decoded.as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fibonacci_frequencies_do_not_panic_and_respect_max_code_len() -> Result<(), String> {
+        // Fibonacci-ratio frequencies are the classic pathological case that
+        // drives Huffman code length toward `symbol_count - 1`; for ~40
+        // symbols that would demand codes far longer than a 32-bit
+        // accumulator can hold were lengths left unbounded.
+        let mut freqs = FreqsTable::new();
+        let (mut a, mut b) = (1u64, 1u64);
+        for token in 0u8..40 {
+            freqs.add(token, a);
+            let next = a + b;
+            a = b;
+            b = next;
+        }
+
+        let tree = construct_huffman_tree(&freqs)?;
+        let lengths = limit_code_lengths(&compute_code_lengths(&tree), MAX_CODE_LEN);
+
+        assert!(lengths.iter().all(|&(_, len)| len > 0 && len <= MAX_CODE_LEN));
+
+        // Canonical assignment must not panic on the limited lengths either.
+        let _ = assign_canonical_codes(&lengths);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_fast_matches_decode() -> Result<(), String> {
+        let text = "Hello World! Hello Huffman!";
+        let message = Huffman::encode(text.as_bytes())?;
+
+        let decoded = Huffman::decode(&message)?;
+        let decoded_fast = Huffman::decode_fast(&message)?;
+
+        assert_eq!(decoded, decoded_fast);
+        assert_eq!(text.as_bytes(), decoded_fast.as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_roundtrip() -> Result<(), String> {
+        let text = "Hello World! Hello Huffman!";
+        let message = Huffman::encode(text.as_bytes())?;
+
+        let bytes = message.to_bytes();
+        assert!(bytes.starts_with(MAGIC));
+
+        let parsed = Message::from_bytes(&bytes)?;
+        let decoded = Huffman::decode(&parsed)?;
+
+        assert_eq!(text.as_bytes(), decoded.as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_rejects_bad_magic() {
+        let bytes = vec![0u8; 32];
+        let err = Message::from_bytes(&bytes).unwrap_err();
+        assert!(err.contains("magic"));
+    }
+
+    #[test]
+    fn test_decode_fast_returns_error_instead_of_panicking_on_inconsistent_table() {
+        // An incomplete code table (Kraft sum < 1) can only arise from a
+        // `Message` built by hand rather than through `from_bytes`, but
+        // `decode_fast` must still fail gracefully on the missing trie
+        // branch rather than panic, matching `decode`'s behavior.
+        let encoded_data: BitVec = [true, true].into_iter().collect();
+        let message = Message {
+            code_lengths: vec![(b'a', 1), (b'b', 2)],
+            encoded_data,
+            original_len: 1,
+        };
+
+        let err = Huffman::decode_fast(&message).unwrap_err();
+        assert!(err.contains("no matching code path"));
+
+        let err = Huffman::decode(&message).unwrap_err();
+        assert!(err.contains("no matching code path"));
+    }
+
+    #[test]
+    fn test_container_rejects_oversized_code_length() {
+        let mut bytes = Message::header_bytes(1, &[(b'a', MAX_CODE_LEN + 1), (b'b', 1)]);
+        bytes.push(0); // payload byte
+        bytes.push(0); // pad-bit count
+        let err = Message::from_bytes(&bytes).unwrap_err();
+        assert!(err.contains("invalid code length"));
+    }
+
+    #[test]
+    fn test_container_rejects_duplicate_code_length_token() {
+        let mut bytes = Message::header_bytes(1, &[(b'a', 2), (b'a', 3)]);
+        bytes.push(0);
+        bytes.push(0);
+        let err = Message::from_bytes(&bytes).unwrap_err();
+        assert!(err.contains("duplicate"));
+    }
+
+    #[test]
+    fn test_container_rejects_incomplete_code_table() {
+        // Two length-2 codes alone leave Kraft's sum at 0.5, not 1: this
+        // table can't be a complete prefix code.
+        let mut bytes = Message::header_bytes(1, &[(b'a', 2), (b'b', 2)]);
+        bytes.push(0);
+        bytes.push(0);
+        let err = Message::from_bytes(&bytes).unwrap_err();
+        assert!(err.contains("complete prefix code"));
+    }
+
+    #[test]
+    fn test_streaming_roundtrip() -> Result<(), String> {
+        let text = "Hello World! Hello Huffman!".repeat(1000);
+
+        let mut source = io::Cursor::new(text.as_bytes().to_vec());
+        let mut container = io::Cursor::new(Vec::new());
+        Huffman::encode_stream(&mut source, &mut container)?;
+
+        let mut output = io::Cursor::new(Vec::new());
+        container.set_position(0);
+        Huffman::decode_stream(&mut container, &mut output)?;
+
+        assert_eq!(text.as_bytes(), output.into_inner().as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_empty_input() -> Result<(), String> {
+        let mut source = io::Cursor::new(Vec::new());
+        let mut container = io::Cursor::new(Vec::new());
+        Huffman::encode_stream(&mut source, &mut container)?;
+
+        let mut output = io::Cursor::new(Vec::new());
+        container.set_position(0);
+        Huffman::decode_stream(&mut container, &mut output)?;
+
+        assert!(output.into_inner().is_empty());
+
+        Ok(())
+    }
 }
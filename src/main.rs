@@ -1,12 +1,10 @@
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use std::process;
 
-mod huffman;
-
 use clap::{Parser, Subcommand};
-use huffman::{Huffman, Message};
-use rmp_serde::{from_slice, to_vec};
+use huffman_rust::huffman::Huffman;
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -54,13 +52,6 @@ fn main() {
 }
 
 fn compress_cmd(input: &PathBuf, output: Option<&PathBuf>) -> Result<(), String> {
-    let data = fs::read(input).map_err(|e| format!("Failed to read input file: {}", e))?;
-
-    let message = Huffman::encode(&data)?;
-
-    let serialized =
-        to_vec(&message).map_err(|e| format!("Failed to serialize compressed message: {}", e))?;
-
     let out_path = match output {
         Some(p) => p.clone(),
         None => {
@@ -76,34 +67,41 @@ fn compress_cmd(input: &PathBuf, output: Option<&PathBuf>) -> Result<(), String>
         }
     };
 
-    fs::write(&out_path, &serialized).map_err(|e| {
+    let mut reader =
+        File::open(input).map_err(|e| format!("Failed to open input file: {}", e))?;
+    let out_file = File::create(&out_path).map_err(|e| {
         format!(
-            "Failed to write compressed file '{}': {}",
+            "Failed to create compressed file '{}': {}",
             out_path.display(),
             e
         )
     })?;
+    let mut writer = BufWriter::new(out_file);
+
+    Huffman::encode_stream(&mut reader, &mut writer)?;
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush compressed file: {}", e))?;
+
+    let input_len = fs::metadata(input)
+        .map_err(|e| format!("Failed to read input file metadata: {}", e))?
+        .len();
+    let output_len = fs::metadata(&out_path)
+        .map_err(|e| format!("Failed to read compressed file metadata: {}", e))?
+        .len();
 
     println!(
         "Compressed '{}' ({} bytes) -> '{}' ({} bytes)",
         input.display(),
-        data.len(),
+        input_len,
         out_path.display(),
-        serialized.len()
+        output_len
     );
 
     Ok(())
 }
 
 fn decompress_cmd(input: &PathBuf, output: Option<&PathBuf>) -> Result<(), String> {
-    let serialized =
-        fs::read(input).map_err(|e| format!("Failed to read compressed file: {}", e))?;
-
-    let message: Message = from_slice(&serialized)
-        .map_err(|e| format!("Failed to deserialize compressed message: {}", e))?;
-
-    let decoded = Huffman::decode(&message)?;
-
     let out_path = match output {
         Some(p) => p.clone(),
         None => {
@@ -119,19 +117,31 @@ fn decompress_cmd(input: &PathBuf, output: Option<&PathBuf>) -> Result<(), Strin
         }
     };
 
-    fs::write(&out_path, &decoded).map_err(|e| {
+    let mut reader =
+        File::open(input).map_err(|e| format!("Failed to open compressed file: {}", e))?;
+    let out_file = File::create(&out_path).map_err(|e| {
         format!(
-            "Failed to write decompressed file '{}': {}",
+            "Failed to create decompressed file '{}': {}",
             out_path.display(),
             e
         )
     })?;
+    let mut writer = BufWriter::new(out_file);
+
+    Huffman::decode_stream(&mut reader, &mut writer)?;
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush decompressed file: {}", e))?;
+
+    let output_len = fs::metadata(&out_path)
+        .map_err(|e| format!("Failed to read decompressed file metadata: {}", e))?
+        .len();
 
     println!(
         "Decompressed '{}' -> '{}' ({} bytes)",
         input.display(),
         out_path.display(),
-        decoded.len()
+        output_len
     );
 
     Ok(())